@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use super::{ConfigError, Value};
+
+impl From<serde_cbor::Error> for ConfigError {
+    fn from(error: serde_cbor::Error) -> Self {
+        ConfigError::new("cbor", &error.to_string())
+    }
+}
+
+/**
+ * A serializable mirror of `Value`, which does not itself derive
+ * `Serialize`/`Deserialize`. Serde records each entry under its variant
+ * name (B, I, F, S or L), so a decoded blob maps back onto the same
+ * `Value` variant.
+ */
+#[derive(Serialize, Deserialize)]
+enum TaggedValue {
+    B(bool),
+    I(i64),
+    F(f64),
+    S(String),
+    L(Vec<TaggedValue>),
+}
+
+impl From<&Value> for TaggedValue {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::B(x) => TaggedValue::B(*x),
+            Value::I(x) => TaggedValue::I(*x),
+            Value::F(x) => TaggedValue::F(*x),
+            Value::S(x) => TaggedValue::S(x.clone()),
+            Value::L(items) => TaggedValue::L(items.iter().map(TaggedValue::from).collect()),
+        }
+    }
+}
+
+impl From<TaggedValue> for Value {
+    fn from(value: TaggedValue) -> Self {
+        match value {
+            TaggedValue::B(x) => Value::B(x),
+            TaggedValue::I(x) => Value::I(x),
+            TaggedValue::F(x) => Value::F(x),
+            TaggedValue::S(x) => Value::S(x),
+            TaggedValue::L(items) => Value::L(items.into_iter().map(Value::from).collect()),
+        }
+    }
+}
+
+/**
+ * Encode a value map to a compact binary blob. The result can be embedded
+ * in a checkpoint file or sent over a socket, and fed back through
+ * `merge_value_map` after a round trip through `read_from_cbor`.
+ */
+pub fn write_to_cbor(value_map: &HashMap<String, Value>) -> Result<Vec<u8>, ConfigError> {
+    let tagged: HashMap<String, TaggedValue> = value_map.iter().map(|(key, value)| (key.clone(), value.into())).collect();
+    Ok(serde_cbor::to_vec(&tagged)?)
+}
+
+/**
+ * Decode a value map from a blob produced by `write_to_cbor`.
+ */
+pub fn read_from_cbor(bytes: &[u8]) -> Result<HashMap<String, Value>, ConfigError> {
+    let tagged: HashMap<String, TaggedValue> = serde_cbor::from_slice(bytes)?;
+    Ok(tagged.into_iter().map(|(key, value)| (key, value.into())).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::Value;
+    use super::{write_to_cbor, read_from_cbor};
+
+    #[test]
+    fn can_round_trip_value_map_through_cbor() {
+        let value_map: HashMap<String, Value> = vec![
+            ("num_zones".to_string(), Value::from(5000)),
+            ("tfinal".to_string(), Value::from(0.2)),
+            ("quiet".to_string(), Value::from(false)),
+            ("outdir".to_string(), Value::from("data")),
+            ("checkpoint_times".to_string(), Value::L(vec![Value::from(0.1), Value::from(0.2)])),
+        ]
+        .into_iter()
+        .collect();
+
+        let bytes = write_to_cbor(&value_map).unwrap();
+        let round_tripped = read_from_cbor(&bytes).unwrap();
+
+        let form = crate::Form::new()
+            .item("num_zones", 5000, "")
+            .item("tfinal", 0.2, "")
+            .item("quiet", false, "")
+            .item("outdir", "data", "")
+            .item("checkpoint_times", Value::L(vec![Value::from(0.0)]), "")
+            .merge_value_map(&round_tripped)
+            .unwrap();
+
+        assert_eq!(i64::from(form.get("num_zones")), 5000);
+        assert_eq!(bool::from(form.get("quiet")), false);
+    }
+
+    #[test]
+    fn read_from_cbor_fails_on_garbage_bytes() {
+        assert!(read_from_cbor(&[0xff, 0x00, 0x01]).is_err());
+    }
+}
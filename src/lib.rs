@@ -1,12 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::path::{Path, PathBuf};
 
 
 
 
 /**
- * Enum (variant) whose kind is either bool, int, float, or string. These are
- * the types of values allowed in a Form.
+ * Enum (variant) whose kind is either bool, int, float, string, or a
+ * homogeneous list of one of those kinds. These are the types of values
+ * allowed in a Form.
  */
 #[derive(Clone)]
 pub enum Value {
@@ -14,12 +16,17 @@ pub enum Value {
     I(i64),
     F(f64),
     S(String),
+    L(Vec<Value>),
 }
 
 impl Value {
 
     /**
-     * Determine whether this value and another are of the same kind.
+     * Determine whether this value and another are of the same kind. Two
+     * lists are the same kind if they are both empty, or if they are both
+     * non-empty with first elements of the same kind. A list with a
+     * declared (non-empty) element kind is never the same kind as an empty
+     * one, since an empty list carries no element kind of its own.
      */
     pub fn same_kind_as(&self, other: &Value) -> bool {
        match (&self, &other) {
@@ -27,6 +34,11 @@ impl Value {
            (Value::I(_), Value::I(_)) => true,
            (Value::F(_), Value::F(_)) => true,
            (Value::S(_), Value::S(_)) => true,
+           (Value::L(a), Value::L(b)) => match (a.first(), b.first()) {
+               (Some(x), Some(y)) => x.same_kind_as(y),
+               (None, None) => true,
+               _ => false,
+           },
            _ => false,
        }
     }
@@ -37,6 +49,7 @@ impl Value {
            (Value::I(a), Value::I(b)) => a == b,
            (Value::F(a), Value::F(b)) => a == b,
            (Value::S(a), Value::S(b)) => a == b,
+           (Value::L(a), Value::L(b)) => a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.same_as(y)),
            _ => false,
        }
     }
@@ -47,6 +60,17 @@ impl Value {
             _ => panic!(),
         }
     }
+
+    /**
+     * Return the string content of this value, or `None` if it is not a
+     * `Value::S`. Unlike `as_str`, this does not panic on other kinds.
+     */
+    fn as_str_if_string(&self) -> Option<&str> {
+        match self {
+            Value::S(s) => Some(s),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Value {
@@ -56,6 +80,7 @@ impl fmt::Display for Value {
             Value::I(x) => x.fmt(f),
             Value::F(x) => x.fmt(f),
             Value::S(x) => x.fmt(f),
+            Value::L(x) => write!(f, "{}", x.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",")),
         }
     }
 }
@@ -65,6 +90,21 @@ impl From<i64>  for Value { fn from(a: i64)  -> Self { Value::I(a) } }
 impl From<f64>  for Value { fn from(a: f64)  -> Self { Value::F(a) } }
 impl From<&str> for Value { fn from(a: &str) -> Self { Value::S(a.into()) } }
 
+/**
+ * Marker for the value kinds that `Form::item_ranged` accepts as a default.
+ * Sealed so that `item_ranged` can only be called with a numeric default,
+ * rather than silently disabling range validation for a bool or string.
+ */
+pub trait Numeric: Into<Value> + private::Sealed {}
+impl Numeric for i64 {}
+impl Numeric for f64 {}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for i64 {}
+    impl Sealed for f64 {}
+}
+
 impl<'a> From<&'a Value> for bool   { fn from(a: &'a Value) -> bool   { match a { Value::B(x) => x.clone(), _ => panic!() } } }
 impl<'a> From<&'a Value> for i64    { fn from(a: &'a Value) -> i64    { match a { Value::I(x) => x.clone(), _ => panic!() } } }
 impl<'a> From<&'a Value> for f64    { fn from(a: &'a Value) -> f64    { match a { Value::F(x) => x.clone(), _ => panic!() } } }
@@ -97,6 +137,46 @@ impl std::error::Error for ConfigError {}
 
 
 
+/**
+ * Validation metadata that can be attached to a `Parameter` and is checked
+ * against incoming values during `merge_value_map`.
+ */
+#[derive(Clone)]
+pub enum Constraint {
+    Range(f64, f64),
+    Choices(Vec<String>),
+}
+
+impl Constraint {
+
+    /**
+     * Check whether a value satisfies this constraint, returning an error
+     * with the given key if it does not.
+     */
+    fn check(&self, key: &str, value: &Value) -> Result<(), ConfigError> {
+        match self {
+            Constraint::Range(min, max) => {
+                let x = match value {
+                    Value::I(x) => *x as f64,
+                    Value::F(x) => *x,
+                    _ => return Ok(()),
+                };
+                if x < *min || x > *max {
+                    Err(ConfigError::new(key, "is out of range"))
+                } else {
+                    Ok(())
+                }
+            }
+            Constraint::Choices(choices) => {
+                match value {
+                    Value::S(s) if !choices.iter().any(|choice| choice == s) => Err(ConfigError::new(key, "is not an allowed value")),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
 /**
  * A value and an about string. This is the value type of the HashMap used in a
  * Form.
@@ -106,6 +186,7 @@ pub struct Parameter {
     pub value: Value,
     pub about: String,
     pub frozen: bool,
+    pub constraint: Option<Constraint>,
 }
 
 
@@ -144,7 +225,43 @@ impl Form {
      * * `about`   - A description of the item for use in user reporting
      */
     pub fn item<T: Into<Value>>(mut self, key: &str, default: T, about: &str) -> Self {
-        self.parameter_map.insert(key.into(), Parameter{value: default.into(), about: about.into(), frozen: false});
+        self.parameter_map.insert(key.into(), Parameter{value: default.into(), about: about.into(), frozen: false, constraint: None});
+        return self
+    }
+
+    /**
+     * Declare a new numeric config item constrained to a closed range
+     * `[min, max]`. A later `merge_value_map` call with a value outside the
+     * range is an error.
+     *
+     * # Arguments
+     *
+     * * `key`     - The name of the config item
+     * * `default` - The default value
+     * * `about`   - A description of the item for use in user reporting
+     * * `min`     - The smallest allowed value
+     * * `max`     - The largest allowed value
+     */
+    pub fn item_ranged<T: Numeric>(mut self, key: &str, default: T, about: &str, min: f64, max: f64) -> Self {
+        self.parameter_map.insert(key.into(), Parameter{value: default.into(), about: about.into(), frozen: false, constraint: Some(Constraint::Range(min, max))});
+        return self
+    }
+
+    /**
+     * Declare a new string config item constrained to a whitelist of
+     * allowed values. A later `merge_value_map` call with a value not in
+     * `choices` is an error.
+     *
+     * # Arguments
+     *
+     * * `key`     - The name of the config item
+     * * `default` - The default value
+     * * `about`   - A description of the item for use in user reporting
+     * * `choices` - The allowed values
+     */
+    pub fn item_choices(mut self, key: &str, default: &str, about: &str, choices: &[&str]) -> Self {
+        let constraint = Constraint::Choices(choices.iter().map(|s| s.to_string()).collect());
+        self.parameter_map.insert(key.into(), Parameter{value: default.into(), about: about.into(), frozen: false, constraint: Some(constraint)});
         return self
     }
 
@@ -184,6 +301,9 @@ impl Form {
                 } else if item.frozen && ! item.value.same_as(new_value) {
                     return Err(ConfigError::new(key, "cannot be modified"));
                 } else {
+                    if let Some(constraint) = &item.constraint {
+                        constraint.check(key, new_value)?;
+                    }
                     item.value = new_value.clone();
                 }
             } else {
@@ -230,6 +350,63 @@ impl Form {
         to_string_map_from_key_val_pairs_allowing_duplicates(args).map(|res| self.merge_string_map(&res))?
     }
 
+    /**
+     * Merge in the contents of a config file, given as lines of `key=value`
+     * pairs. A line of the form `@include path` is resolved first, relative
+     * to the directory containing `path`, so its settings can be overridden
+     * by the including file. Files may be layered arbitrarily deeply, but
+     * an include chain that revisits a file is an error.
+     *
+     * # Arguments
+     *
+     * * `path` - The config file to load
+     */
+    pub fn merge_file(self, path: &Path) -> Result<Self, ConfigError> {
+        let mut visited = HashSet::new();
+        self.merge_file_visiting(path, &mut visited)
+    }
+
+    /**
+     * Merge in a single file, tracking the set of ancestor files currently
+     * being resolved so a file transitively including itself is caught. The
+     * canonicalized path is removed from `visited` again once this file (and
+     * everything it includes) has finished resolving, so that two sibling
+     * includes of the same shared file (a "diamond") are not mistaken for a
+     * cycle.
+     */
+    fn merge_file_visiting(self, path: &Path, visited: &mut HashSet<PathBuf>) -> Result<Self, ConfigError> {
+        let canonical = path.canonicalize().map_err(|_| ConfigError::new(&path.to_string_lossy(), "could not be read"))?;
+
+        if !visited.insert(canonical.clone()) {
+            return Err(ConfigError::new(&path.to_string_lossy(), "forms a circular include"));
+        }
+
+        let result = self.merge_file_contents(path, visited);
+        visited.remove(&canonical);
+        result
+    }
+
+    fn merge_file_contents(self, path: &Path, visited: &mut HashSet<PathBuf>) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|_| ConfigError::new(&path.to_string_lossy(), "could not be read"))?;
+        let directory = path.parent().unwrap_or_else(|| Path::new("."));
+
+        let mut result = self;
+        let mut lines = Vec::new();
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            } else if let Some(include) = line.strip_prefix("@include") {
+                result = result.merge_file_visiting(&directory.join(include.trim()), visited)?;
+            } else {
+                lines.push(line.to_string());
+            }
+        }
+        let items = to_string_map_from_key_val_pairs(lines)?;
+        result.merge_string_map(&items)
+    }
+
     /**
      * Freeze a parameter with the given name, if it exists, or otherwise panic.
      */
@@ -239,6 +416,72 @@ impl Form {
         return self;
     }
 
+    /**
+     * Resolve `${key}` references embedded in string values against the
+     * current values of other parameters in this form. Resolution proceeds
+     * in passes: each pass substitutes any reference whose target is itself
+     * already fully resolved, until either no unresolved references remain
+     * or a pass makes no progress, in which case the remaining references
+     * form a cycle.
+     *
+     * # Example
+     * ```
+     * let form = kind_config::Form::new()
+     *     .item("run_name", "run1", "")
+     *     .item("outdir", "runs/${run_name}", "")
+     *     .resolve_references()
+     *     .unwrap();
+     * assert_eq!(form.get("outdir").as_str(), "runs/run1");
+     * ```
+     */
+    pub fn resolve_references(mut self) -> Result<Self, ConfigError> {
+        let mut unresolved: Vec<String> = self.parameter_map.iter()
+            .filter_map(|(key, parameter)| match &parameter.value {
+                Value::S(s) if !find_references(s).is_empty() => Some(key.clone()),
+                _ => None,
+            })
+            .collect();
+
+        for key in &unresolved {
+            let s = self.parameter_map[key].value.as_str().to_string();
+            for reference in find_references(&s) {
+                if !self.parameter_map.contains_key(&reference) {
+                    return Err(ConfigError::new(&reference, "is not a valid key"));
+                }
+            }
+        }
+
+        while !unresolved.is_empty() {
+            let mut progress = false;
+            let mut still_unresolved = Vec::new();
+
+            for key in unresolved {
+                let s = self.parameter_map[&key].value.as_str().to_string();
+                let references = find_references(&s);
+
+                if references.iter().any(|reference| still_unresolved.contains(reference) || self.parameter_map[reference].value.as_str_if_string().map_or(false, |v| !find_references(v).is_empty())) {
+                    still_unresolved.push(key);
+                    continue;
+                }
+
+                let mut resolved = s;
+                for reference in &references {
+                    let token = format!("${{{}}}", reference);
+                    let replacement = self.parameter_map[reference].value.to_string();
+                    resolved = resolved.replace(&token, &replacement);
+                }
+                self.parameter_map.get_mut(&key).unwrap().value = Value::S(resolved);
+                progress = true;
+            }
+
+            if !progress && !still_unresolved.is_empty() {
+                return Err(ConfigError::new(&still_unresolved[0], "has a circular reference"));
+            }
+            unresolved = still_unresolved;
+        }
+        Ok(self)
+    }
+
     /**
      * Return a hash map of the (key, value) items, stripping out the about
      * messages. If the HDF5 feature is enabled, the result can be written
@@ -248,6 +491,30 @@ impl Form {
         self.parameter_map.iter().map(|(key, parameter)| (key.clone(), parameter.value.clone())).collect()
     }
 
+    /**
+     * Return a new form containing only the keys declared under a dotted
+     * section prefix (e.g. `grid.num_zones`), with the prefix and its
+     * trailing dot stripped from the resulting keys. This lets a large
+     * configuration be organized into subsystem sections while the
+     * underlying storage stays a flat map.
+     *
+     * # Example
+     * ```
+     * let form = kind_config::Form::new()
+     *     .item("grid.num_zones", 5000, "")
+     *     .item("grid.spacing", 1.0, "");
+     * let grid = form.subform("grid");
+     * assert_eq!(i64::from(grid.get("num_zones")), 5000);
+     * ```
+     */
+    pub fn subform(&self, prefix: &str) -> Form {
+        let section = format!("{}.", prefix);
+        let parameter_map = self.parameter_map.iter()
+            .filter_map(|(key, parameter)| key.strip_prefix(&section).map(|rest| (rest.to_string(), parameter.clone())))
+            .collect();
+        Form{parameter_map}
+    }
+
     /**
      * Return the number of items.
      */
@@ -297,11 +564,18 @@ impl Form {
 
         for (k, v) in dict {
             let parameter = self.parameter_map.get(k).ok_or(ConfigError::new(&k, "is not a valid key"))?;
-            let value = match parameter.value {
+            let value = match &parameter.value {
                 B(_) => v.parse().map(|x| B(x)).map_err(|_| ConfigError::new(k, "is a badly formed bool")),
                 I(_) => v.parse().map(|x| I(x)).map_err(|_| ConfigError::new(k, "is a badly formed int")),
                 F(_) => v.parse().map(|x| F(x)).map_err(|_| ConfigError::new(k, "is a badly formed float")),
                 S(_) => v.parse().map(|x| S(x)).map_err(|_| ConfigError::new(k, "is a badly formed string")),
+                L(items) => v.split(',').map(|part| match items.first() {
+                    Some(B(_)) => part.trim().parse().map(B).map_err(|_| ConfigError::new(k, "is a badly formed list")),
+                    Some(I(_)) => part.trim().parse().map(I).map_err(|_| ConfigError::new(k, "is a badly formed list")),
+                    Some(F(_)) => part.trim().parse().map(F).map_err(|_| ConfigError::new(k, "is a badly formed list")),
+                    Some(S(_)) => Ok(S(part.trim().to_string())),
+                    Some(L(_)) | None => Err(ConfigError::new(k, "is a badly formed list")),
+                }).collect::<Result<Vec<Value>, ConfigError>>().map(L),
             }?;
             result.insert(k.to_string(), value);
         }
@@ -346,6 +620,26 @@ fn to_string_map_from_key_val_pairs_general<T: IntoIterator<Item=U>, U: Into<Str
     Ok(result)
 }
 
+/**
+ * Find all the `${key}` reference tokens in a string, in order of
+ * appearance.
+ */
+fn find_references(s: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut rest = s;
+
+    while let Some(start) = rest.find("${") {
+        rest = &rest[start + 2..];
+        if let Some(end) = rest.find('}') {
+            result.push(rest[..end].to_string());
+            rest = &rest[end + 1..];
+        } else {
+            break;
+        }
+    }
+    result
+}
+
 pub fn to_string_map_from_key_val_pairs<T: IntoIterator<Item=U>, U: Into<String>>(args: T) -> Result<HashMap<String, String>, ConfigError> {
     to_string_map_from_key_val_pairs_general(args, false)
 }
@@ -357,41 +651,101 @@ pub fn to_string_map_from_key_val_pairs_allowing_duplicates<T: IntoIterator<Item
 
 
 
+// ============================================================================
+#[cfg(feature="cbor")]
+pub mod cbor;
+
+
+
+
 // ============================================================================
 #[cfg(feature="hdf5")]
 pub mod io {
     use hdf5;
     use super::*;
 
+    /**
+     * Write a value map to an HDF5 group. Dotted keys (e.g. `grid.spacing`)
+     * are written into a nested `hdf5::Group` per section rather than being
+     * flattened, mirroring `Form::subform`.
+     */
     pub fn write_to_hdf5(group: &hdf5::Group, value_map: &HashMap::<String, Value>) -> Result<(), hdf5::Error> {
         use hdf5::types::VarLenAscii;
 
+        let mut sections = HashMap::<String, HashMap<String, Value>>::new();
+
         for (key, value) in value_map {
+            if let Some((section, rest)) = key.split_once('.') {
+                sections.entry(section.to_string()).or_default().insert(rest.to_string(), value.clone());
+                continue;
+            }
             match &value {
                 Value::B(x) => group.new_dataset::<bool>().create(key, ())?.write_scalar(x),
                 Value::I(x) => group.new_dataset::<i64>().create(key, ())?.write_scalar(x),
                 Value::F(x) => group.new_dataset::<f64>().create(key, ())?.write_scalar(x),
                 Value::S(x) => group.new_dataset::<VarLenAscii>().create(key, ())?.write_scalar(&VarLenAscii::from_ascii(&x).unwrap()),
+                Value::L(items) => match items.first() {
+                    Some(Value::B(_)) => {
+                        let data: Vec<bool> = items.iter().map(|v| bool::from(v)).collect();
+                        group.new_dataset::<bool>().shape(data.len()).create(key)?.write(&data)
+                    }
+                    Some(Value::I(_)) => {
+                        let data: Vec<i64> = items.iter().map(|v| i64::from(v)).collect();
+                        group.new_dataset::<i64>().shape(data.len()).create(key)?.write(&data)
+                    }
+                    Some(Value::F(_)) => {
+                        let data: Vec<f64> = items.iter().map(|v| f64::from(v)).collect();
+                        group.new_dataset::<f64>().shape(data.len()).create(key)?.write(&data)
+                    }
+                    Some(Value::S(_)) | Some(Value::L(_)) | None => {
+                        let data: Vec<VarLenAscii> = items.iter().map(|v| VarLenAscii::from_ascii(&String::from(v)).unwrap()).collect();
+                        group.new_dataset::<VarLenAscii>().shape(data.len()).create(key)?.write(&data)
+                    }
+                },
             }?;
         }
+        for (section, sub_map) in &sections {
+            write_to_hdf5(&group.create_group(section)?, sub_map)?;
+        }
         Ok(())
     }
 
+    /**
+     * Read a value map back from an HDF5 group written by `write_to_hdf5`.
+     * Nested groups are read back as dotted keys.
+     */
     pub fn read_from_hdf5(group: &hdf5::Group) -> Result<HashMap::<String, Value>, hdf5::Error> {
         use hdf5::types::VarLenAscii;
         let mut values = HashMap::<String, Value>::new();
 
         for key in group.member_names()? {
-            let dtype = group.dataset(&key)?.dtype()?;
+            if let Ok(subgroup) = group.group(&key) {
+                for (leaf, value) in read_from_hdf5(&subgroup)? {
+                    values.insert(format!("{}.{}", key, leaf), value);
+                }
+                continue;
+            }
+            let dataset = group.dataset(&key)?;
+            let dtype = dataset.dtype()?;
+            let is_list = !dataset.shape().is_empty();
+
             let value =
-            if dtype.is::<bool>() {
-                group.dataset(&key)?.read_scalar::<bool>().map(|x| Value::from(x))
+            if is_list && dtype.is::<bool>() {
+                dataset.read_1d::<bool>().map(|xs| Value::L(xs.iter().map(|x| Value::from(*x)).collect()))
+            } else if is_list && dtype.is::<i64>() {
+                dataset.read_1d::<i64>().map(|xs| Value::L(xs.iter().map(|x| Value::from(*x)).collect()))
+            } else if is_list && dtype.is::<f64>() {
+                dataset.read_1d::<f64>().map(|xs| Value::L(xs.iter().map(|x| Value::from(*x)).collect()))
+            } else if is_list {
+                dataset.read_1d::<VarLenAscii>().map(|xs| Value::L(xs.iter().map(|x| Value::from(x.as_str())).collect()))
+            } else if dtype.is::<bool>() {
+                dataset.read_scalar::<bool>().map(|x| Value::from(x))
             } else if dtype.is::<i64>() {
-                group.dataset(&key)?.read_scalar::<i64>().map(|x| Value::from(x))
+                dataset.read_scalar::<i64>().map(|x| Value::from(x))
             } else if dtype.is::<f64>() {
-                group.dataset(&key)?.read_scalar::<f64>().map(|x| Value::from(x))
+                dataset.read_scalar::<f64>().map(|x| Value::from(x))
             } else {
-                group.dataset(&key)?.read_scalar::<VarLenAscii>().map(|x| Value::from(x.as_str()))
+                dataset.read_scalar::<VarLenAscii>().map(|x| Value::from(x.as_str()))
             }?;
             values.insert(key.to_string(), value);
         }
@@ -503,6 +857,154 @@ mod tests {
         make_example_form().merge_value_map(&args).unwrap();
     }
 
+    #[test]
+    fn can_resolve_references() {
+        let form = Form::new()
+            .item("run_name", "run1", "")
+            .item("outdir", "runs/${run_name}", "")
+            .item("logfile", "${outdir}/log.txt", "")
+            .resolve_references()
+            .unwrap();
+        assert_eq!(form.get("outdir").as_str(), "runs/run1");
+        assert_eq!(form.get("logfile").as_str(), "runs/run1/log.txt");
+    }
+
+    #[test]
+    #[should_panic]
+    fn resolve_references_fails_on_circular_reference() {
+        Form::new()
+            .item("a", "${b}", "")
+            .item("b", "${a}", "")
+            .resolve_references()
+            .unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn resolve_references_fails_on_undeclared_key() {
+        Form::new()
+            .item("outdir", "runs/${run_name}", "")
+            .resolve_references()
+            .unwrap();
+    }
+
+    #[test]
+    fn can_get_subform() {
+        let form = Form::new()
+            .item("grid.num_zones", 5000, "")
+            .item("grid.spacing", 1.0, "")
+            .item("solver.tolerance", 1.0e-8, "");
+
+        let grid = form.subform("grid");
+        assert_eq!(grid.len(), 2);
+        assert_eq!(i64::from(grid.get("num_zones")), 5000);
+        assert_eq!(f64::from(grid.get("spacing")), 1.0);
+    }
+
+    #[test]
+    fn item_ranged_enforces_bounds() {
+        let form = Form::new().item_ranged("rk_order", 2, "", 1.0, 3.0);
+
+        let ok: HashMap<String, Value> = vec![("rk_order".to_string(), Value::from(3))].into_iter().collect();
+        assert!(form.merge_value_map(&ok).is_ok());
+
+        let too_low: HashMap<String, Value> = vec![("rk_order".to_string(), Value::from(0))].into_iter().collect();
+        assert!(Form::new().item_ranged("rk_order", 2, "", 1.0, 3.0).merge_value_map(&too_low).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn item_ranged_rejects_out_of_range() {
+        let args: HashMap<String, Value> = vec![("rk_order".to_string(), Value::from(7))].into_iter().collect();
+        Form::new().item_ranged("rk_order", 2, "", 1.0, 3.0).merge_value_map(&args).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn item_choices_rejects_disallowed_value() {
+        let args: HashMap<String, Value> = vec![("outdir".to_string(), Value::from("typo"))].into_iter().collect();
+        Form::new().item_choices("outdir", "data", "", &["data", "scratch"]).merge_value_map(&args).unwrap();
+    }
+
+    #[test]
+    fn can_merge_string_list() {
+        let form = Form::new()
+            .item("checkpoint_times", Value::L(vec![Value::from(0.0)]), "")
+            .merge_string_args(vec!["checkpoint_times=0.1,0.2,0.5"])
+            .unwrap();
+        assert_eq!(form.get("checkpoint_times").to_string(), "0.1,0.2,0.5");
+    }
+
+    #[test]
+    #[should_panic]
+    fn merge_string_list_fails_with_badly_formed_element() {
+        Form::new()
+            .item("checkpoint_times", Value::L(vec![Value::from(0.0)]), "")
+            .merge_string_args(vec!["checkpoint_times=0.1,oops,0.5"])
+            .unwrap();
+    }
+
+    #[test]
+    fn list_same_kind_as_requires_matching_elements() {
+        let ints = Value::L(vec![Value::from(1), Value::from(2)]);
+        let more_ints = Value::L(vec![Value::from(3)]);
+        let strings = Value::L(vec![Value::from("a")]);
+        let empty = Value::L(vec![]);
+
+        assert!(ints.same_kind_as(&more_ints));
+        assert!(!ints.same_kind_as(&strings));
+        assert!(empty.same_kind_as(&Value::L(vec![])));
+        assert!(!ints.same_kind_as(&empty));
+        assert!(!empty.same_kind_as(&ints));
+    }
+
+    #[test]
+    #[should_panic]
+    fn merge_value_map_fails_assigning_into_empty_declared_list() {
+        let args: HashMap<String, Value> = vec![("xs".to_string(), Value::L(vec![Value::from(1)]))].into_iter().collect();
+        Form::new().item("xs", Value::L(vec![]), "").merge_value_map(&args).unwrap();
+    }
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("kind_config_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn can_merge_file_with_diamond_include() {
+        let dir = scratch_dir("diamond");
+        std::fs::write(dir.join("common.conf"), "num_zones=1000\n").unwrap();
+        std::fs::write(dir.join("override1.conf"), "@include common.conf\ntfinal=0.4\n").unwrap();
+        std::fs::write(dir.join("override2.conf"), "@include common.conf\nrk_order=1\n").unwrap();
+        std::fs::write(dir.join("main.conf"), "@include override1.conf\n@include override2.conf\nquiet=true\n").unwrap();
+
+        let form = make_example_form()
+            .merge_file(&dir.join("main.conf"))
+            .unwrap();
+
+        assert_eq!(i64::from(form.get("num_zones")), 1000);
+        assert_eq!(f64::from(form.get("tfinal")), 0.4);
+        assert_eq!(i64::from(form.get("rk_order")), 1);
+        assert_eq!(bool::from(form.get("quiet")), true);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn merge_file_fails_with_circular_include() {
+        let dir = scratch_dir("cycle");
+        std::fs::write(dir.join("a.conf"), "@include b.conf\n").unwrap();
+        std::fs::write(dir.join("b.conf"), "@include a.conf\n").unwrap();
+
+        let result = make_example_form().merge_file(&dir.join("a.conf"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+    }
+
     #[cfg(feature="hdf5")]
     #[cfg(test)]
     mod io_tests {